@@ -16,33 +16,44 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use common_path;
+use libc::{self, c_int};
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Display, Formatter};
 use std::fs;
+use std::io;
 use std::iter;
 use std::os::unix::fs as unixfs;
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::rc::Rc;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tempfile::{self, TempDir};
 
 use super::error::{RedoError, RedoErrorKind};
 use super::helpers::{self, RedoPath, RedoPathBuf};
 
 const ENV_BASE: &str = "REDO_BASE";
+pub const ENV_CFG: &str = "REDO_CFG";
 pub const ENV_COLOR: &str = "REDO_COLOR";
 pub const ENV_DEBUG: &str = "REDO_DEBUG";
 pub const ENV_DEBUG_LOCKS: &str = "REDO_DEBUG_LOCKS";
 pub const ENV_DEBUG_PIDS: &str = "REDO_DEBUG_PIDS";
 pub(crate) const ENV_DEPTH: &str = "REDO_DEPTH";
+pub const ENV_JOBS: &str = "REDO_JOBS";
 pub const ENV_KEEP_GOING: &str = "REDO_KEEP_GOING";
 const ENV_LOCKS_BROKEN: &str = "REDO_LOCKS_BROKEN";
 pub const ENV_LOG: &str = "REDO_LOG";
 pub(crate) const ENV_LOG_INODE: &str = "REDO_LOG_INODE";
+const ENV_MAKEFLAGS: &str = "MAKEFLAGS";
 pub const ENV_NO_OOB: &str = "REDO_NO_OOB";
+pub const ENV_PRESERVE_XATTRS: &str = "REDO_PRESERVE_XATTRS";
 pub const ENV_PRETTY: &str = "REDO_PRETTY";
+pub const ENV_PROFILE: &str = "REDO_PROFILE";
 pub(crate) const ENV_PWD: &str = "REDO_PWD";
 const ENV_REDO: &str = "REDO";
 const ENV_RUNID: &str = "REDO_RUNID";
@@ -71,16 +82,28 @@ pub struct Env {
     log: i32,
     log_inode: OsString,
     color: i32,
+    preserve_xattrs: i32,
     pretty: i32,
     pub(crate) shuffle: bool,
     pub(crate) startdir: PathBuf,
     pub(crate) runid: Option<i64>,
     pub(crate) unlocked: bool,
     pub(crate) no_oob: bool,
+    jobserver: Option<JobserverPool>,
+    cfg: CfgSet,
+    profile_path: Option<PathBuf>,
 
     redo_links_dir: Option<Rc<TempDir>>,
 }
 
+/// A GNU Make-compatible pool of job tokens shared with subprocesses
+/// via the `MAKEFLAGS` environment variable.
+#[derive(Clone, Debug)]
+enum JobserverPool {
+    Pipe { read_fd: RawFd, write_fd: RawFd },
+    Fifo { path: PathBuf },
+}
+
 impl Env {
     /// Start a session (if needed) for a command that does need the state db.
     pub fn init<P: AsRef<RedoPath>>(targets: &[P]) -> Result<Env, RedoError> {
@@ -131,6 +154,12 @@ impl Env {
             new_path.push(old_path);
             env::set_var("PATH", new_path);
             env::set_var(ENV_REDO, exe_path);
+
+            if parse_jobserver_auth().is_none() {
+                let jobs = get_int(ENV_JOBS, 1).max(1);
+                let pool = Env::make_jobserver_pool(jobs)?;
+                export_jobserver_auth(&pool);
+            }
         }
         if !get_bool(ENV_BASE) {
             let targets: Vec<&RedoPath> = if targets.is_empty() {
@@ -176,6 +205,9 @@ impl Env {
             env::set_var(ENV_BASE, base.unwrap_or(orig_base));
             env::set_var(ENV_STARTDIR, cwd);
         }
+        if env::var_os(ENV_CFG).is_none() {
+            env::set_var(ENV_CFG, CfgSet::host().to_env_string());
+        }
         Ok(Env {
             is_toplevel,
             redo_links_dir,
@@ -207,6 +239,37 @@ impl Env {
         Ok(d)
     }
 
+    /// Create a fresh jobserver pipe holding `jobs - 1` tokens (the calling
+    /// process implicitly owns one token and never writes it into the pipe).
+    ///
+    /// The fds are marked `FD_CLOEXEC` so that arbitrary subprocesses (e.g.
+    /// `cc`, `cp`, anything a `.do` script shells out to) don't inherit them
+    /// and hold the write end open forever. Only a `.do` child that actually
+    /// needs jobserver access should see these fds, via `clear_cloexec`
+    /// right before that specific `exec`.
+    fn make_jobserver_pool(jobs: i64) -> Result<JobserverPool, RedoError> {
+        // `pipe2(..., O_CLOEXEC)` isn't available on all unix targets (e.g.
+        // Apple platforms only expose plain `pipe`), so create the pipe
+        // portably and set `FD_CLOEXEC` on each fd ourselves right after.
+        let mut fds: [c_int; 2] = [-1, -1];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(RedoError::opaque_error(io::Error::last_os_error()));
+        }
+        let (read_fd, write_fd) = (fds[0] as RawFd, fds[1] as RawFd);
+        for fd in [read_fd, write_fd] {
+            set_cloexec(fd);
+        }
+        for _ in 0..jobs.saturating_sub(1) {
+            let byte = [b'+'];
+            let n =
+                unsafe { libc::write(write_fd, byte.as_ptr() as *const libc::c_void, byte.len()) };
+            if n != byte.len() as isize {
+                return Err(RedoError::opaque_error(io::Error::last_os_error()));
+            }
+        }
+        Ok(JobserverPool::Pipe { read_fd, write_fd })
+    }
+
     /// Start a session (if needed) for a command that needs no state db.
     pub fn init_no_state() -> Result<Env, RedoError> {
         let mut is_toplevel = false;
@@ -251,6 +314,7 @@ impl Env {
             log: get_int(ENV_LOG, 1) as i32,
             log_inode: env::var_os(ENV_LOG_INODE).unwrap_or_default(),
             color: get_int(ENV_COLOR, 0) as i32,
+            preserve_xattrs: get_int(ENV_PRESERVE_XATTRS, 0) as i32,
             pretty: get_int(ENV_PRETTY, 0) as i32,
             shuffle: get_bool(ENV_SHUFFLE),
             startdir: env::var_os(ENV_STARTDIR).unwrap_or_default().into(),
@@ -260,6 +324,20 @@ impl Env {
             },
             unlocked: get_bool(ENV_UNLOCKED),
             no_oob: get_bool(ENV_NO_OOB),
+            jobserver: parse_jobserver_auth().map(|pool| {
+                if let JobserverPool::Pipe { read_fd, write_fd } = &pool {
+                    set_cloexec(*read_fd);
+                    set_cloexec(*write_fd);
+                }
+                pool
+            }),
+            cfg: env::var(ENV_CFG)
+                .ok()
+                .map(|s| CfgSet::from_env_string(&s))
+                .unwrap_or_else(CfgSet::host),
+            profile_path: env::var_os(ENV_PROFILE)
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from),
             redo_links_dir: None,
         };
         if v.depth.contains(|c| c != ' ') {
@@ -368,6 +446,20 @@ impl Env {
         }
     }
 
+    /// Whether to preserve the previous target's extended attributes
+    /// (including the `security.selinux` label) across the atomic rename
+    /// that publishes a freshly built target.
+    #[inline]
+    pub fn preserve_xattrs(&self) -> OptionalBool {
+        if self.preserve_xattrs == 0 {
+            OptionalBool::Off
+        } else if self.preserve_xattrs == 1 {
+            OptionalBool::Auto
+        } else {
+            OptionalBool::On
+        }
+    }
+
     #[inline]
     pub fn startdir(&self) -> &Path {
         &self.startdir
@@ -394,6 +486,233 @@ impl Env {
         self.runid = Some(runid);
         env::set_var(ENV_RUNID, runid.to_string());
     }
+
+    /// Evaluate a Cargo-style `cfg(...)` expression against the host
+    /// platform description cached on this `Env`.
+    pub fn cfg_matches(&self, expr: &str) -> Result<bool, RedoError> {
+        let parsed = CfgExpr::parse(expr)?;
+        Ok(parsed.eval(&self.cfg))
+    }
+
+    /// Record the start of a target build for the `REDO_PROFILE` trace, if
+    /// profiling is enabled. Returns `None` (a no-op handle) otherwise.
+    pub fn profile_begin(&self, target: &str) -> Option<ProfileHandle> {
+        self.profile_path.as_ref()?;
+        Some(ProfileHandle {
+            target: target.to_string(),
+            start_micros: now_micros(),
+        })
+    }
+
+    /// Record the end of a target build started with `profile_begin`,
+    /// appending a Chrome tracing JSON event to the `REDO_PROFILE` file.
+    pub fn profile_end(
+        &self,
+        handle: Option<ProfileHandle>,
+        rebuilt: bool,
+    ) -> Result<(), RedoError> {
+        let (path, handle) = match (&self.profile_path, handle) {
+            (Some(path), Some(handle)) => (path, handle),
+            _ => return Ok(()),
+        };
+        let end_micros = now_micros();
+        let dur = end_micros.saturating_sub(handle.start_micros);
+        let event = format!(
+            "{{\"name\":{:?},\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":{},\"tid\":{},\
+             \"args\":{{\"depth\":{:?},\"rebuilt\":{}}}}},\n",
+            handle.target,
+            handle.start_micros,
+            dur,
+            self.runid.unwrap_or(0),
+            process::id(),
+            self.depth,
+            rebuilt,
+        );
+        append_profile_event(path, &event)
+    }
+
+    /// Whether this process joined a GNU Make-compatible jobserver pool,
+    /// either one it created itself (toplevel) or one it inherited via
+    /// `MAKEFLAGS`.
+    #[inline]
+    pub fn has_jobserver(&self) -> bool {
+        self.jobserver.is_some()
+    }
+
+    /// Block until an extra job token is available. The calling process's
+    /// own implicit token is not affected; this is only for jobs beyond the
+    /// first that want to run concurrently.
+    pub fn acquire_token(&self) -> Result<(), RedoError> {
+        let read_fd = match &self.jobserver {
+            Some(JobserverPool::Pipe { read_fd, .. }) => *read_fd,
+            Some(JobserverPool::Fifo { path }) => {
+                return acquire_token_fifo(path);
+            }
+            None => return Ok(()),
+        };
+        let mut byte = [0u8; 1];
+        loop {
+            let n = unsafe { libc::read(read_fd, byte.as_mut_ptr() as *mut libc::c_void, 1) };
+            if n == 1 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(RedoError::opaque_error(err));
+            }
+        }
+    }
+
+    /// Return a token previously obtained from `acquire_token`.
+    pub fn release_token(&self) -> Result<(), RedoError> {
+        let write_fd = match &self.jobserver {
+            Some(JobserverPool::Pipe { write_fd, .. }) => *write_fd,
+            Some(JobserverPool::Fifo { path }) => {
+                return release_token_fifo(path);
+            }
+            None => return Ok(()),
+        };
+        let byte = [b'+'];
+        let n = unsafe { libc::write(write_fd, byte.as_ptr() as *const libc::c_void, 1) };
+        if n != 1 {
+            return Err(RedoError::opaque_error(io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Gather what a caller needs to hand this process's jobserver pool to
+    /// a `.do` child it's about to spawn: the `--jobserver-auth=...`
+    /// fragment for that child's `MAKEFLAGS`, and the fds (if any) that
+    /// need `FD_CLOEXEC` cleared for the child to inherit them.
+    ///
+    /// The pool's fds are marked `FD_CLOEXEC` (by `make_jobserver_pool` for
+    /// a new pool, by `inherit` for one taken from `MAKEFLAGS`) precisely
+    /// so that subprocesses don't inherit them by default. This
+    /// method intentionally does *not* clear that flag itself: doing so
+    /// from this (parent) process would change the fd table shared by
+    /// every subsequent `exec`, not just this one child. Instead, the
+    /// caller should clear it from inside a `pre_exec` hook (e.g.
+    /// `std::os::unix::process::CommandExt::pre_exec`) that only runs in
+    /// the forked copy of this process on its way to becoming that child —
+    /// calling `env::clear_cloexec` there for each returned fd.
+    pub fn jobserver_auth_for_child(&self) -> Option<JobserverChildAuth> {
+        let pool = self.jobserver.as_ref()?;
+        let fds = match pool {
+            JobserverPool::Pipe { read_fd, write_fd } => vec![*read_fd, *write_fd],
+            JobserverPool::Fifo { .. } => Vec::new(),
+        };
+        Some(JobserverChildAuth {
+            fds,
+            makeflags_fragment: jobserver_auth_string(pool),
+        })
+    }
+}
+
+/// What a caller needs to hand this process's jobserver pool to a spawned
+/// `.do` child. See `Env::jobserver_auth_for_child`.
+pub struct JobserverChildAuth {
+    /// Fds to clear `FD_CLOEXEC` on, from within a `pre_exec` hook scoped to
+    /// the forked child only.
+    pub fds: Vec<RawFd>,
+    /// The `--jobserver-auth=...` fragment to append to the child's
+    /// `MAKEFLAGS`.
+    pub makeflags_fragment: String,
+}
+
+/// Set `FD_CLOEXEC` on `fd`. Best-effort: errors are ignored, matching the
+/// rest of this module's fd plumbing, since there's nothing more specific
+/// to do if the fd has already gone away.
+fn set_cloexec(fd: RawFd) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags >= 0 {
+            libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC);
+        }
+    }
+}
+
+/// Clear `FD_CLOEXEC` on `fd` so it survives the next `exec`. Intended to be
+/// called only from within a `pre_exec` hook scoped to a single forked
+/// child (see `Env::jobserver_auth_for_child`) so the change doesn't leak to
+/// every subprocess of the calling process. Best-effort, for the same
+/// reason as `set_cloexec`.
+pub(crate) fn clear_cloexec(fd: RawFd) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags >= 0 {
+            libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
+        }
+    }
+}
+
+/// Parse `--jobserver-auth=<r>,<w>` (or the older `--jobserver-fds=`, or the
+/// `fifo:<path>` form used by Make 4.3+) out of `MAKEFLAGS`.
+fn parse_jobserver_auth() -> Option<JobserverPool> {
+    let makeflags = env::var(ENV_MAKEFLAGS).ok()?;
+    for word in makeflags.split_whitespace() {
+        let value = match word
+            .strip_prefix("--jobserver-auth=")
+            .or_else(|| word.strip_prefix("--jobserver-fds="))
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        if let Some(path) = value.strip_prefix("fifo:") {
+            return Some(JobserverPool::Fifo {
+                path: PathBuf::from(path),
+            });
+        }
+        let mut parts = value.splitn(2, ',');
+        let read_fd: RawFd = match parts.next().and_then(|s| s.parse().ok()) {
+            Some(fd) => fd,
+            None => continue,
+        };
+        let write_fd: RawFd = match parts.next().and_then(|s| s.parse().ok()) {
+            Some(fd) => fd,
+            None => continue,
+        };
+        return Some(JobserverPool::Pipe { read_fd, write_fd });
+    }
+    None
+}
+
+/// Format the `--jobserver-auth=...` fragment that advertises a pool to
+/// subprocesses, the same form GNU Make itself writes into `MAKEFLAGS`.
+fn jobserver_auth_string(pool: &JobserverPool) -> String {
+    match pool {
+        JobserverPool::Pipe { read_fd, write_fd } => {
+            format!("--jobserver-auth={},{}", read_fd, write_fd)
+        }
+        JobserverPool::Fifo { path } => format!("--jobserver-auth=fifo:{}", path.display()),
+    }
+}
+
+/// Advertise a freshly created jobserver pool to subprocesses via
+/// `MAKEFLAGS`, the same variable GNU Make itself uses.
+fn export_jobserver_auth(pool: &JobserverPool) {
+    let auth = jobserver_auth_string(pool);
+    let mut flags = env::var(ENV_MAKEFLAGS).unwrap_or_default();
+    if !flags.is_empty() {
+        flags.push(' ');
+    }
+    flags.push_str(&auth);
+    env::set_var(ENV_MAKEFLAGS, flags);
+}
+
+fn acquire_token_fifo(path: &Path) -> Result<(), RedoError> {
+    use std::io::Read;
+    let mut f = fs::File::open(path).map_err(RedoError::opaque_error)?;
+    let mut byte = [0u8; 1];
+    f.read_exact(&mut byte).map_err(RedoError::opaque_error)
+}
+
+fn release_token_fifo(path: &Path) -> Result<(), RedoError> {
+    use std::io::Write;
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(RedoError::opaque_error)?;
+    f.write_all(b"+").map_err(RedoError::opaque_error)
 }
 
 fn get_int<K: AsRef<OsStr>>(key: K, default: i64) -> i64 {
@@ -474,3 +793,305 @@ impl From<OptionalBool> for Option<bool> {
         }
     }
 }
+
+/// The set of `cfg(...)` atoms and key/value pairs that describe the host
+/// platform, used to evaluate conditional `.do` targets.
+#[derive(Clone, Debug, Default)]
+pub struct CfgSet {
+    atoms: HashSet<String>,
+    values: HashMap<String, String>,
+}
+
+impl CfgSet {
+    /// Build the `cfg` set describing the platform this binary was compiled
+    /// for (and is running on).
+    fn host() -> CfgSet {
+        let mut atoms = HashSet::new();
+        if cfg!(unix) {
+            atoms.insert("unix".to_string());
+        }
+        if cfg!(windows) {
+            atoms.insert("windows".to_string());
+        }
+
+        let mut values = HashMap::new();
+        values.insert("target_os".to_string(), env::consts::OS.to_string());
+        values.insert("target_arch".to_string(), env::consts::ARCH.to_string());
+        values.insert("target_family".to_string(), env::consts::FAMILY.to_string());
+        let pointer_width = if cfg!(target_pointer_width = "64") {
+            "64"
+        } else if cfg!(target_pointer_width = "32") {
+            "32"
+        } else if cfg!(target_pointer_width = "16") {
+            "16"
+        } else {
+            "unknown"
+        };
+        values.insert(
+            "target_pointer_width".to_string(),
+            pointer_width.to_string(),
+        );
+
+        CfgSet { atoms, values }
+    }
+
+    fn has_atom(&self, atom: &str) -> bool {
+        self.atoms.contains(atom)
+    }
+
+    fn value_matches(&self, key: &str, value: &str) -> bool {
+        self.values.get(key).map_or(false, |v| v == value)
+    }
+
+    /// Serialize for propagation through the `REDO_CFG` environment
+    /// variable, e.g. `unix,target_os="linux",target_pointer_width="64"`.
+    fn to_env_string(&self) -> String {
+        let mut parts: Vec<String> = self.atoms.iter().cloned().collect();
+        parts.sort();
+        let mut kv: Vec<(&String, &String)> = self.values.iter().collect();
+        kv.sort();
+        for (k, v) in kv {
+            parts.push(format!("{}=\"{}\"", k, v));
+        }
+        parts.join(",")
+    }
+
+    /// Parse the format produced by `to_env_string`.
+    fn from_env_string(s: &str) -> CfgSet {
+        let mut atoms = HashSet::new();
+        let mut values = HashMap::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((k, v)) => {
+                    values.insert(k.to_string(), v.trim_matches('"').to_string());
+                }
+                None => {
+                    atoms.insert(part.to_string());
+                }
+            }
+        }
+        CfgSet { atoms, values }
+    }
+}
+
+/// A parsed Cargo-style `cfg(...)` expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum CfgExpr {
+    Atom(String),
+    KeyEq(String, String),
+    All(Vec<CfgExpr>),
+    Any(Vec<CfgExpr>),
+    Not(Box<CfgExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum CfgToken {
+    Ident(String),
+    Str(String),
+    Eq,
+    Comma,
+    LParen,
+    RParen,
+}
+
+impl CfgExpr {
+    fn parse(input: &str) -> Result<CfgExpr, RedoError> {
+        let tokens = cfg_tokenize(input)?;
+        let mut pos = 0;
+        let expr = CfgExpr::parse_expr(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(cfg_parse_error(format!(
+                "unexpected trailing input in cfg expression: {:?}",
+                input
+            )));
+        }
+        Ok(expr)
+    }
+
+    fn parse_expr(tokens: &[CfgToken], pos: &mut usize) -> Result<CfgExpr, RedoError> {
+        let ident = match tokens.get(*pos) {
+            Some(CfgToken::Ident(s)) => s.clone(),
+            other => {
+                return Err(cfg_parse_error(format!(
+                    "expected identifier in cfg expression, got {:?}",
+                    other
+                )))
+            }
+        };
+        *pos += 1;
+        match ident.as_str() {
+            "all" => Ok(CfgExpr::All(CfgExpr::parse_list(tokens, pos)?)),
+            "any" => Ok(CfgExpr::Any(CfgExpr::parse_list(tokens, pos)?)),
+            "not" => {
+                CfgExpr::expect(tokens, pos, &CfgToken::LParen)?;
+                let inner = CfgExpr::parse_expr(tokens, pos)?;
+                CfgExpr::expect(tokens, pos, &CfgToken::RParen)?;
+                Ok(CfgExpr::Not(Box::new(inner)))
+            }
+            _ => match tokens.get(*pos) {
+                Some(CfgToken::Eq) => {
+                    *pos += 1;
+                    match tokens.get(*pos) {
+                        Some(CfgToken::Str(s)) => {
+                            *pos += 1;
+                            Ok(CfgExpr::KeyEq(ident, s.clone()))
+                        }
+                        other => Err(cfg_parse_error(format!(
+                            "expected string literal after `=` in cfg expression, got {:?}",
+                            other
+                        ))),
+                    }
+                }
+                _ => Ok(CfgExpr::Atom(ident)),
+            },
+        }
+    }
+
+    fn parse_list(tokens: &[CfgToken], pos: &mut usize) -> Result<Vec<CfgExpr>, RedoError> {
+        CfgExpr::expect(tokens, pos, &CfgToken::LParen)?;
+        let mut items = Vec::new();
+        loop {
+            if tokens.get(*pos) == Some(&CfgToken::RParen) {
+                break;
+            }
+            items.push(CfgExpr::parse_expr(tokens, pos)?);
+            match tokens.get(*pos) {
+                Some(CfgToken::Comma) => {
+                    *pos += 1;
+                }
+                _ => break,
+            }
+        }
+        CfgExpr::expect(tokens, pos, &CfgToken::RParen)?;
+        Ok(items)
+    }
+
+    fn expect(tokens: &[CfgToken], pos: &mut usize, want: &CfgToken) -> Result<(), RedoError> {
+        if tokens.get(*pos) == Some(want) {
+            *pos += 1;
+            Ok(())
+        } else {
+            Err(cfg_parse_error(format!(
+                "expected {:?} in cfg expression, got {:?}",
+                want,
+                tokens.get(*pos)
+            )))
+        }
+    }
+
+    fn eval(&self, cfg: &CfgSet) -> bool {
+        match self {
+            CfgExpr::Atom(a) => cfg.has_atom(a),
+            CfgExpr::KeyEq(k, v) => cfg.value_matches(k, v),
+            CfgExpr::All(items) => items.iter().all(|e| e.eval(cfg)),
+            CfgExpr::Any(items) => items.iter().any(|e| e.eval(cfg)),
+            CfgExpr::Not(inner) => !inner.eval(cfg),
+        }
+    }
+}
+
+fn cfg_tokenize(input: &str) -> Result<Vec<CfgToken>, RedoError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = input.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(CfgToken::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(CfgToken::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(CfgToken::Comma);
+            i += 1;
+        } else if c == '=' {
+            tokens.push(CfgToken::Eq);
+            i += 1;
+        } else if c == '"' {
+            i += 1;
+            let start = i;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(cfg_parse_error(format!(
+                    "unterminated string literal in cfg expression: {:?}",
+                    input
+                )));
+            }
+            tokens.push(CfgToken::Str(chars[start..i].iter().collect()));
+            i += 1; // closing quote
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(CfgToken::Ident(chars[start..i].iter().collect()));
+        } else {
+            return Err(cfg_parse_error(format!(
+                "unexpected character {:?} in cfg expression: {:?}",
+                c, input
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+fn cfg_parse_error(message: String) -> RedoError {
+    RedoError::new(message).with_kind(RedoErrorKind::InvalidCfgExpr)
+}
+
+/// An in-flight target build being timed for the `REDO_PROFILE` trace.
+/// Obtained from `Env::profile_begin`; pass to `Env::profile_end` when the
+/// target finishes.
+pub struct ProfileHandle {
+    target: String,
+    start_micros: u64,
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Append one Chrome tracing JSON event to the profile file, writing the
+/// `{"traceEvents":[` header the first time. Since many redo processes
+/// append to the same file across a build, the closing `]}` is
+/// intentionally never written; `chrome://tracing` and Perfetto both accept
+/// a trace with a missing trailer.
+fn append_profile_event(path: &Path, event: &str) -> Result<(), RedoError> {
+    use std::io::Write;
+
+    // Many redo processes can reach this at once. `create_new` is an
+    // atomic O_CREAT|O_EXCL at the OS level, so exactly one of them wins
+    // the race and writes the header; everyone else sees `AlreadyExists`
+    // and falls back to a plain append. A separate `exists()` check before
+    // `open()` would instead let two processes both observe "missing" and
+    // both write the header, corrupting the trace.
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+    {
+        Ok(mut f) => {
+            f.write_all(b"{\"traceEvents\":[\n")
+                .map_err(RedoError::opaque_error)?;
+            f.write_all(event.as_bytes())
+                .map_err(RedoError::opaque_error)
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let mut f = fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(RedoError::opaque_error)?;
+            f.write_all(event.as_bytes())
+                .map_err(RedoError::opaque_error)
+        }
+        Err(e) => Err(RedoError::opaque_error(e)),
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2021 Ross Light
+// Copyright 2010-2018 Avery Pennarun and contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::error::Error as StdError;
+use std::ffi::OsString;
+use std::fmt::{self, Display, Formatter};
+
+/// The error type returned throughout this crate.
+#[derive(Debug)]
+pub struct RedoError {
+    message: String,
+    kind: RedoErrorKind,
+    source: Option<Box<dyn StdError + Send + Sync + 'static>>,
+}
+
+impl RedoError {
+    /// Create an error from an ad-hoc message with no particular
+    /// [`RedoErrorKind`].
+    pub fn new<S: Into<String>>(message: S) -> RedoError {
+        RedoError {
+            message: message.into(),
+            kind: RedoErrorKind::Other,
+            source: None,
+        }
+    }
+
+    /// Wrap an arbitrary error (e.g. an I/O error) whose specific kind the
+    /// caller doesn't need to distinguish from any other opaque failure.
+    pub fn opaque_error<E: StdError + Send + Sync + 'static>(err: E) -> RedoError {
+        RedoError {
+            message: err.to_string(),
+            kind: RedoErrorKind::Other,
+            source: Some(Box::new(err)),
+        }
+    }
+
+    /// Attach a [`RedoErrorKind`] to this error, replacing the default
+    /// `Other`.
+    pub fn with_kind(mut self, kind: RedoErrorKind) -> RedoError {
+        self.kind = kind;
+        self
+    }
+
+    /// The structured kind of this error: `Other` unless a more specific
+    /// kind was attached.
+    #[inline]
+    pub fn kind(&self) -> &RedoErrorKind {
+        &self.kind
+    }
+}
+
+impl Display for RedoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for RedoError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source
+            .as_ref()
+            .map(|e| e.as_ref() as &(dyn StdError + 'static))
+    }
+}
+
+impl From<RedoErrorKind> for RedoError {
+    fn from(kind: RedoErrorKind) -> RedoError {
+        RedoError {
+            message: kind.to_string(),
+            kind,
+            source: None,
+        }
+    }
+}
+
+/// Structured classification of a [`RedoError`], for callers that need to
+/// branch on *why* something failed rather than just report it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RedoErrorKind {
+    /// A target path couldn't be used as-is (e.g. it has no parent
+    /// directory).
+    InvalidTarget(OsString),
+    /// A `cfg(...)` guard expression (see `Env::cfg_matches`) failed to
+    /// parse.
+    InvalidCfgExpr,
+    /// Catch-all for errors that don't need a more specific kind.
+    Other,
+}
+
+impl Display for RedoErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RedoErrorKind::InvalidTarget(t) => write!(f, "invalid target: {:?}", t),
+            RedoErrorKind::InvalidCfgExpr => write!(f, "invalid cfg(...) expression"),
+            RedoErrorKind::Other => write!(f, "error"),
+        }
+    }
+}
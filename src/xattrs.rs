@@ -0,0 +1,77 @@
+// Copyright 2021 Ross Light
+// Copyright 2010-2018 Avery Pennarun and contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Extended attribute (and SELinux label) preservation across the atomic
+//! rename that publishes a built target, gated by `Env::preserve_xattrs`.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use super::env::Env;
+use super::error::RedoError;
+
+const SELINUX_XATTR: &str = "security.selinux";
+
+/// Before a freshly built temp file is renamed into place at `dest_path`,
+/// copy over the extended attributes (including the SELinux label) that the
+/// published target should carry. No-op unless `env.preserve_xattrs()` is
+/// enabled.
+///
+/// If `dest_path` already has a prior version, its xattrs are snapshotted
+/// and applied verbatim. Otherwise there's nothing to snapshot, so the
+/// default label the filesystem/policy would assign a new file at that path
+/// is computed instead (see `default_selinux_label`) and applied alone.
+pub fn preserve_before_rename(
+    env: &Env,
+    temp_path: &Path,
+    dest_path: &Path,
+) -> Result<(), RedoError> {
+    if !env.preserve_xattrs().unwrap_or(false) {
+        return Ok(());
+    }
+
+    let mut pairs = Vec::new();
+    if dest_path.exists() {
+        for name in xattr::list(dest_path).map_err(RedoError::opaque_error)? {
+            if let Some(value) = xattr::get(dest_path, &name).map_err(RedoError::opaque_error)? {
+                pairs.push((name, value));
+            }
+        }
+    } else if let Some(label) = default_selinux_label(dest_path)? {
+        pairs.push((OsString::from(SELINUX_XATTR), label));
+    }
+
+    for (name, value) in pairs {
+        xattr::set(temp_path, &name, &value).map_err(RedoError::opaque_error)?;
+    }
+    Ok(())
+}
+
+/// Determine the SELinux label a brand-new file at `dest_path` would
+/// receive, by creating (and immediately removing) a throwaway file
+/// alongside it and reading back whatever label the kernel/policy assigned.
+/// Returns `Ok(None)` if SELinux isn't in enforcing/permissive mode (no
+/// `security.selinux` xattr shows up) rather than treating that as an
+/// error.
+fn default_selinux_label(dest_path: &Path) -> Result<Option<Vec<u8>>, RedoError> {
+    let probe_path = dest_path.with_file_name(format!(".redo-xattr-probe.{}", std::process::id()));
+    fs::File::create(&probe_path).map_err(RedoError::opaque_error)?;
+    let result = xattr::get(&probe_path, SELINUX_XATTR).map_err(RedoError::opaque_error);
+    let _ = fs::remove_file(&probe_path);
+    result
+}